@@ -1,14 +1,41 @@
 use std::{
-    cmp::min, ffi::OsStr, fs::File, io, mem, num::Wrapping, path::PathBuf, thread, time::Duration,
+    cmp::min,
+    ffi::OsStr,
+    io, mem,
+    num::Wrapping,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
-use clap::Parser;
+use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, ZstdDecoder};
+use clap::{Parser, ValueEnum};
+use futures_util::stream::FuturesUnordered;
+use futures_util::StreamExt as _;
 use pgn_reader::{BufferedReader, Color, Outcome, RawHeader, SanPlus, Skip, Visitor};
 use rand::{distributions::OpenClosed01, rngs::SmallRng, Rng, SeedableRng};
-use serde::Serialize;
-use serde_with::{serde_as, DisplayFromStr, SpaceSeparator, StringWithSeparator};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use serde_with::{base64::Base64, serde_as, DisplayFromStr, SpaceSeparator, StringWithSeparator};
+use shakmaty::{fen::Fen, CastlingMode, Chess, Position};
+use tokio::{
+    io::{AsyncRead, BufReader},
+    sync::{mpsc, Mutex},
+    task,
+};
+use tokio_util::io::SyncIoBridge;
+
+#[derive(Debug, Copy, Clone, ValueEnum)]
+enum Format {
+    Json,
+    Postcard,
+}
 
-#[derive(Debug, Serialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Copy, Clone)]
 #[serde(rename_all = "camelCase")]
 enum Speed {
     UltraBullet,
@@ -50,24 +77,222 @@ impl Speed {
     }
 }
 
+/// MSB-first bit buffer, as produced by [`pack_moves`] and consumed by
+/// [`unpack_moves`].
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    cur: u8,
+    bit: u8,
+}
+
+impl BitWriter {
+    fn push(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            self.cur |= (((value >> i) & 1) as u8) << (7 - self.bit);
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bytes.push(self.cur);
+                self.cur = 0;
+                self.bit = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit > 0 {
+            self.bytes.push(self.cur);
+        }
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    bit: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            bytes,
+            pos: 0,
+            bit: 0,
+        }
+    }
+
+    fn pop(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for _ in 0..count {
+            let byte = self.bytes.get(self.pos).copied().unwrap_or(0);
+            value = (value << 1) | u32::from((byte >> (7 - self.bit)) & 1);
+            self.bit += 1;
+            if self.bit == 8 {
+                self.pos += 1;
+                self.bit = 0;
+            }
+        }
+        value
+    }
+}
+
+/// Number of bits needed to index `count` alternatives, i.e. `ceil(log2(count))`.
+/// A single alternative needs zero bits.
+fn bits_for(count: usize) -> u32 {
+    if count <= 1 {
+        0
+    } else {
+        usize::BITS - (count - 1).leading_zeros()
+    }
+}
+
+/// Parses and replays the starting position for [`pack_moves`]/[`unpack_moves`].
+/// Returns `None` for a FEN that doesn't parse or isn't a legal position,
+/// rather than panicking: unlike the header text elsewhere in this file,
+/// this is validated by actually running it through shakmaty, and a
+/// mislabeled variant or corrupt PGN at Lichess-dump scale is expected to
+/// turn up games that don't replay cleanly.
+fn start_position(fen: Option<&str>) -> Option<Chess> {
+    match fen {
+        Some(fen) => fen
+            .parse::<Fen>()
+            .ok()?
+            .into_position(CastlingMode::Standard)
+            .ok(),
+        None => Some(Chess::default()),
+    }
+}
+
+/// Packs `moves` into the minimum number of bits per ply, by replaying the
+/// game from `fen` (or the standard starting position) and writing the index
+/// of the played move among shakmaty's legal moves in that position.
+///
+/// Returns `None` if the game doesn't replay cleanly against shakmaty (bad
+/// FEN, or a move that isn't legal in the position it's played from) instead
+/// of panicking, so one bad game doesn't take down the whole file's import.
+fn pack_moves(fen: Option<&str>, moves: &[SanPlus]) -> Option<Vec<u8>> {
+    let mut pos = start_position(fen)?;
+    let mut writer = BitWriter::default();
+
+    for san in moves {
+        let mv = san.san.to_move(&pos).ok()?;
+        let legals = pos.legal_moves();
+        let index = legals.iter().position(|legal| *legal == mv)?;
+        writer.push(index as u32, bits_for(legals.len()));
+        pos.play_unchecked(&mv);
+    }
+
+    Some(writer.finish())
+}
+
+/// Inverse of [`pack_moves`]. `ply` must be the number of moves that were
+/// packed, since the bit stream itself carries no length. The indexer that
+/// actually decodes `packed_moves` lives server-side; this copy exists so
+/// encoder and decoder are provably kept in lockstep, exercised by the
+/// round-trip tests below.
+#[allow(dead_code)]
+fn unpack_moves(fen: Option<&str>, ply: usize, packed: &[u8]) -> Vec<SanPlus> {
+    let mut pos = start_position(fen).expect("valid fen");
+    let mut reader = BitReader::new(packed);
+    let mut moves = Vec::with_capacity(ply);
+
+    for _ in 0..ply {
+        let legals = pos.legal_moves();
+        let index = reader.pop(bits_for(legals.len())) as usize;
+        let mv = legals[index].clone();
+        moves.push(SanPlus::from_move(&pos, &mv));
+        pos.play_unchecked(&mv);
+    }
+
+    moves
+}
+
+/// Sidecar state persisted next to a PGN file, so a crashed import can resume
+/// without reimporting games the server already acknowledged.
+#[derive(Serialize, Deserialize, Clone)]
+struct Checkpoint {
+    offset: u64,
+    last_id: Option<String>,
+}
+
+fn checkpoint_path(pgn: &Path) -> PathBuf {
+    let mut path = pgn.as_os_str().to_owned();
+    path.push(".checkpoint.json");
+    PathBuf::from(path)
+}
+
+async fn load_checkpoint(pgn: &Path) -> Option<Checkpoint> {
+    let data = tokio::fs::read(checkpoint_path(pgn)).await.ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+/// Writes `checkpoint`, unless a newer one is already on disk. Uploads for
+/// the same file can complete out of submission order under `--concurrency`,
+/// so without `lock` serializing the read-compare-write below, two uploads
+/// completing close together could both see themselves as newest and both
+/// write, with the slower `tokio::fs::write` winning regardless of which
+/// batch's offset was actually larger. `lock` must be the same mutex for
+/// every batch of a given file.
+async fn save_checkpoint(pgn: &Path, checkpoint: &Checkpoint, lock: &Mutex<()>) {
+    let _guard = lock.lock().await;
+
+    if let Some(existing) = load_checkpoint(pgn).await {
+        if existing.offset >= checkpoint.offset {
+            return;
+        }
+    }
+
+    let data = serde_json::to_vec(checkpoint).expect("encode checkpoint");
+    tokio::fs::write(checkpoint_path(pgn), data)
+        .await
+        .expect("write checkpoint");
+}
+
+/// Tracks how many bytes have been read from `inner`, so the importer can
+/// record a resumable offset alongside each checkpoint.
+struct CountingReader<R> {
+    inner: R,
+    offset: Arc<AtomicU64>,
+}
+
+impl<R> CountingReader<R> {
+    fn new(inner: R, offset: Arc<AtomicU64>) -> CountingReader<R> {
+        CountingReader { inner, offset }
+    }
+}
+
+impl<R: io::Read> io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.offset.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
 struct Batch {
     filename: PathBuf,
     games: Vec<Game>,
+    checkpoint: Checkpoint,
+    checkpoint_lock: Arc<Mutex<()>>,
 }
 
 struct Importer {
-    tx: crossbeam::channel::Sender<Batch>,
+    tx: mpsc::Sender<Batch>,
     filename: PathBuf,
     batch_size: usize,
+    pack_moves: bool,
+    offset: Arc<AtomicU64>,
+    resume_before: Option<String>,
+    checkpoint_lock: Arc<Mutex<()>>,
 
-    rng: SmallRng,
     current: Game,
     skip: bool,
     batch: Vec<Game>,
 }
 
 #[serde_as]
-#[derive(Default, Serialize, Debug)]
+#[derive(Default, Serialize, Deserialize, Debug)]
 struct Game {
     variant: Option<String>,
     speed: Option<Speed>,
@@ -80,9 +305,16 @@ struct Game {
     winner: Option<Color>,
     #[serde_as(as = "StringWithSeparator<SpaceSeparator, SanPlus>")]
     moves: Vec<SanPlus>,
+    // No `skip_serializing_if` here: Postcard's encoding is purely
+    // positional, so conditionally omitting a field would desync every
+    // field after it for every Postcard-formatted upload, not just the ones
+    // with `--pack-moves` on.
+    ply: Option<u16>,
+    #[serde_as(as = "Option<Base64>")]
+    packed_moves: Option<Vec<u8>>,
 }
 
-#[derive(Default, Serialize, Debug)]
+#[derive(Default, Serialize, Deserialize, Debug, PartialEq)]
 struct Player {
     name: Option<String>,
     rating: Option<u16>,
@@ -90,19 +322,22 @@ struct Player {
 
 impl Importer {
     fn new(
-        tx: crossbeam::channel::Sender<Batch>,
+        tx: mpsc::Sender<Batch>,
         filename: PathBuf,
         batch_size: usize,
+        pack_moves: bool,
+        offset: Arc<AtomicU64>,
+        resume: Option<Checkpoint>,
+        checkpoint_lock: Arc<Mutex<()>>,
     ) -> Importer {
         Importer {
             tx,
             filename,
             batch_size,
-            rng: SmallRng::from_seed([
-                0x19, 0x29, 0xab, 0x17, 0xc6, 0xfa, 0xb0, 0xe9, 0x4b, 0x44, 0xd8, 0x07, 0x09, 0xbf,
-                0x1d, 0x87, 0xbd, 0xd8, 0xb3, 0x2f, 0xe1, 0xe2, 0xa0, 0x1a, 0x9e, 0x30, 0x98, 0xd7,
-                0xef, 0xd5, 0x7a, 0x1d,
-            ]),
+            pack_moves,
+            offset,
+            resume_before: resume.and_then(|checkpoint| checkpoint.last_id),
+            checkpoint_lock,
             current: Game::default(),
             skip: false,
             batch: Vec::with_capacity(batch_size),
@@ -110,15 +345,44 @@ impl Importer {
     }
 
     pub fn send(&mut self) {
+        // A file whose game count is a multiple of the batch size already
+        // flushed everything in end_game; without this guard the final,
+        // unconditional send() at EOF would ship an empty batch whose
+        // checkpoint has last_id: None, overwriting the real one.
+        if self.batch.is_empty() {
+            return;
+        }
+
+        let checkpoint = Checkpoint {
+            offset: self.offset.load(Ordering::Relaxed),
+            last_id: self.batch.last().and_then(|game| game.id.clone()),
+        };
+
         self.tx
-            .send(Batch {
+            .blocking_send(Batch {
                 filename: self.filename.clone(),
                 games: mem::replace(&mut self.batch, Vec::with_capacity(self.batch_size)),
+                checkpoint,
+                checkpoint_lock: self.checkpoint_lock.clone(),
             })
             .expect("send");
     }
 }
 
+/// Probabilistic-sampling roll for `end_headers`, keyed by the game id
+/// rather than drawn from a continuous RNG. A resumed run that skips past
+/// already-imported games never "burns" rolls for them, so keying by id
+/// (instead of by position in the stream) is what makes the accepted
+/// sample identical whether or not the import was interrupted.
+fn acceptance_roll(id: Option<&str>) -> f64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64 ^ 0x19c6_fab0_4b44_d807;
+    for &byte in id.unwrap_or("").as_bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    SmallRng::seed_from_u64(hash).sample(OpenClosed01)
+}
+
 impl Visitor for Importer {
     type Result = ();
 
@@ -178,6 +442,16 @@ impl Visitor for Importer {
     }
 
     fn end_headers(&mut self) -> Skip {
+        if let Some(resume_before) = self.resume_before.take() {
+            // Still fast-forwarding through games the server already
+            // acknowledged before the previous run was interrupted.
+            if self.current.id.as_deref() != Some(resume_before.as_str()) {
+                self.resume_before = Some(resume_before);
+            }
+            self.skip = true;
+            return Skip(true);
+        }
+
         let rating =
             (self.current.white.rating.unwrap_or(0) + self.current.black.rating.unwrap_or(0)) / 2;
 
@@ -224,7 +498,7 @@ impl Visitor for Importer {
             self.current.white.rating.unwrap_or(0),
             self.current.black.rating.unwrap_or(0),
         ) >= 1501
-            && probability >= self.rng.sample(OpenClosed01)
+            && probability >= acceptance_roll(self.current.id.as_deref())
             && !self.skip;
 
         self.skip = !accept;
@@ -241,7 +515,39 @@ impl Visitor for Importer {
 
     fn end_game(&mut self) {
         if !self.skip {
-            self.batch.push(mem::take(&mut self.current));
+            let mut game = mem::take(&mut self.current);
+
+            // pack_moves() only knows standard chess rules (see
+            // start_position); Chess960, Crazyhouse, Atomic, etc. keep their
+            // plain SAN moves instead of risking a panic or a silently wrong
+            // packed index.
+            let standard = game
+                .variant
+                .as_deref()
+                .map_or(true, |name| name == "Standard");
+
+            if self.pack_moves && standard {
+                match pack_moves(game.fen.as_deref(), &game.moves) {
+                    Some(packed) => {
+                        game.packed_moves = Some(packed);
+                        game.ply = Some(game.moves.len() as u16);
+                        game.moves = Vec::new();
+                    }
+                    None => {
+                        // Replaying the game against shakmaty failed (bad
+                        // FEN, or a move that wasn't legal where it was
+                        // played) -- skip just this game rather than
+                        // crashing the whole file's import.
+                        eprintln!(
+                            "Skipping game {:?} in {:?}: does not replay cleanly",
+                            game.id, self.filename
+                        );
+                        return;
+                    }
+                }
+            }
+
+            self.batch.push(game);
 
             if self.batch.len() >= self.batch_size {
                 self.send();
@@ -256,66 +562,269 @@ struct Args {
     endpoint: String,
     #[clap(long, default_value = "200")]
     batch_size: usize,
+    #[clap(long, value_enum, default_value = "json")]
+    format: Format,
+    #[clap(long, default_value = "4")]
+    concurrency: usize,
+    #[clap(long)]
+    pack_moves: bool,
     pgns: Vec<PathBuf>,
 }
 
-fn main() -> Result<(), io::Error> {
-    let args = Args::parse();
+async fn open_reader(path: &Path) -> io::Result<Pin<Box<dyn AsyncRead + Send>>> {
+    let file = tokio::fs::File::open(path).await?;
+    let buffered = BufReader::new(file);
 
-    let (tx, rx) = crossbeam::channel::bounded::<Batch>(50);
-
-    let bg = thread::spawn(move || {
-        let mut spinner_idx = Wrapping(0);
-        let spinner = &['⣾', '⣽', '⣻', '⢿', '⡿', '⣟', '⣯', '⣷'];
-
-        let client = reqwest::blocking::Client::builder()
-            .timeout(Duration::from_secs(60))
-            .build()
-            .expect("client");
-
-        while let Ok(batch) = rx.recv() {
-            let res = client
-                .put(format!("{}/import/lichess", args.endpoint))
-                .json(&batch.games)
-                .send()
-                .expect("send batch");
-
-            spinner_idx += Wrapping(1);
-
-            println!(
-                "{} {:?}: {}: {} - {}",
-                spinner[spinner_idx.0 % spinner.len()],
-                batch.filename,
-                batch
-                    .games
-                    .last()
-                    .and_then(|g| g.date.as_ref())
-                    .unwrap_or(&String::new()),
-                res.status(),
-                res.text().expect("decode response")
-            );
+    Ok(match path.extension().and_then(OsStr::to_str) {
+        Some("bz2") => {
+            println!("Reading compressed {:?} ...", path);
+            Box::pin(BzDecoder::new(buffered)) as Pin<Box<dyn AsyncRead + Send>>
+        }
+        Some("gz") => {
+            println!("Reading compressed {:?} ...", path);
+            Box::pin(GzipDecoder::new(buffered)) as Pin<Box<dyn AsyncRead + Send>>
         }
-    });
+        Some("zst") => {
+            println!("Reading compressed {:?} ...", path);
+            Box::pin(ZstdDecoder::new(buffered)) as Pin<Box<dyn AsyncRead + Send>>
+        }
+        _ => {
+            println!("Reading {:?} ...", path);
+            Box::pin(buffered) as Pin<Box<dyn AsyncRead + Send>>
+        }
+    })
+}
 
-    for arg in args.pgns {
-        let file = File::open(&arg)?;
+/// Uploads one batch to `{endpoint}/import/lichess` in the requested `format`.
+///
+/// The `/import/lichess` route itself (and its `application/x-postcard`
+/// decoder) lives in the server, which is out of scope of this crate — this
+/// binary only controls what it sends. `--format postcard` is not safe to
+/// use against a server that hasn't been updated to accept that content
+/// type; until it has, stick to `--format json`.
+async fn upload_batch(
+    client: reqwest::Client,
+    endpoint: &str,
+    format: Format,
+    batch: Batch,
+    spinner_idx: usize,
+) -> reqwest::Result<()> {
+    let spinner = &['⣾', '⣽', '⣻', '⢿', '⡿', '⣟', '⣯', '⣷'];
+
+    let req = client.put(format!("{}/import/lichess", endpoint));
+    let req = match format {
+        Format::Json => req.json(&batch.games),
+        Format::Postcard => req
+            .header(CONTENT_TYPE, "application/x-postcard")
+            .body(postcard::to_stdvec(&batch.games).expect("encode postcard")),
+    };
+    let res = req.send().await?;
+    let status = res.status();
+
+    println!(
+        "{} {:?}: {}: {} - {}",
+        spinner[spinner_idx % spinner.len()],
+        batch.filename,
+        batch
+            .games
+            .last()
+            .and_then(|g| g.date.as_ref())
+            .unwrap_or(&String::new()),
+        status,
+        res.text().await.expect("decode response")
+    );
+
+    if status.is_success() {
+        save_checkpoint(&batch.filename, &batch.checkpoint, &batch.checkpoint_lock).await;
+    }
 
-        let uncompressed: Box<dyn io::Read> = if arg.extension() == Some(OsStr::new("bz2")) {
-            println!("Reading compressed {:?} ...", arg);
-            Box::new(bzip2::read::MultiBzDecoder::new(file))
-        } else {
-            println!("Reading {:?} ...", arg);
-            Box::new(file)
-        };
+    Ok(())
+}
 
-        let mut reader = BufferedReader::new(uncompressed);
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
 
-        let mut importer = Importer::new(tx.clone(), arg, args.batch_size);
-        reader.read_all(&mut importer)?;
-        importer.send();
+    let (tx, mut rx) = mpsc::channel::<Batch>(50);
+
+    let parsing = {
+        let pgns = args.pgns.clone();
+        let batch_size = args.batch_size;
+        let pack_moves = args.pack_moves;
+        task::spawn(async move {
+            for path in pgns {
+                let resume = load_checkpoint(&path).await;
+                let reader = open_reader(&path).await?;
+                let tx = tx.clone();
+                let offset = Arc::new(AtomicU64::new(0));
+                let checkpoint_lock = Arc::new(Mutex::new(()));
+                let counted = CountingReader::new(SyncIoBridge::new(reader), offset.clone());
+                task::spawn_blocking(move || -> io::Result<()> {
+                    let mut reader = BufferedReader::new(counted);
+                    let mut importer = Importer::new(
+                        tx,
+                        path,
+                        batch_size,
+                        pack_moves,
+                        offset,
+                        resume,
+                        checkpoint_lock,
+                    );
+                    reader.read_all(&mut importer)?;
+                    importer.send();
+                    Ok(())
+                })
+                .await
+                .expect("parse task")?;
+            }
+            Ok::<_, io::Error>(())
+        })
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .build()?;
+
+    let mut uploads = FuturesUnordered::new();
+    let mut spinner_idx = Wrapping(0usize);
+
+    loop {
+        tokio::select! {
+            Some(batch) = rx.recv(), if uploads.len() < args.concurrency => {
+                spinner_idx += Wrapping(1);
+                uploads.push(upload_batch(client.clone(), &args.endpoint, args.format, batch, spinner_idx.0));
+            }
+            Some(res) = uploads.next(), if !uploads.is_empty() => {
+                res.expect("upload batch");
+            }
+            else => break,
+        }
     }
 
-    drop(tx);
-    bg.join().expect("bg join");
+    parsing.await.expect("parsing task")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sans(moves: &[&str]) -> Vec<SanPlus> {
+        moves
+            .iter()
+            .map(|mv| mv.parse().expect("valid san"))
+            .collect()
+    }
+
+    fn san_strings(moves: &[SanPlus]) -> Vec<String> {
+        moves.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn pack_unpack_roundtrip_from_start() {
+        let moves = sans(&["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+        let packed = pack_moves(None, &moves).expect("replays cleanly");
+        let unpacked = unpack_moves(None, moves.len(), &packed);
+        assert_eq!(san_strings(&moves), san_strings(&unpacked));
+    }
+
+    #[test]
+    fn pack_unpack_roundtrip_from_explicit_fen() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let moves = sans(&["d4", "d5", "c4"]);
+        let packed = pack_moves(Some(fen), &moves).expect("replays cleanly");
+        let unpacked = unpack_moves(Some(fen), moves.len(), &packed);
+        assert_eq!(san_strings(&moves), san_strings(&unpacked));
+    }
+
+    #[test]
+    fn pack_moves_rejects_an_unparseable_fen() {
+        assert!(start_position(Some("not a fen")).is_none());
+        assert!(pack_moves(Some("not a fen"), &sans(&["e4"])).is_none());
+    }
+
+    #[test]
+    fn pack_moves_rejects_an_illegal_move() {
+        // No white pawn can reach e5 in one move from the starting position.
+        assert!(pack_moves(None, &sans(&["e5"])).is_none());
+    }
+
+    #[test]
+    fn bits_for_a_single_alternative_is_zero() {
+        // A position with a single legal move must consume zero bits.
+        assert_eq!(bits_for(0), 0);
+        assert_eq!(bits_for(1), 0);
+        assert_eq!(bits_for(2), 1);
+        assert_eq!(bits_for(3), 2);
+        assert_eq!(bits_for(4), 2);
+        assert_eq!(bits_for(5), 3);
+
+        let mut writer = BitWriter::default();
+        writer.push(0, bits_for(1));
+        assert!(writer.finish().is_empty());
+    }
+
+    fn assert_game_roundtrips(game: Game) {
+        // Postcard is a positional format: unlike JSON it carries no field
+        // names, so a field that serializes conditionally (e.g. via
+        // `skip_serializing_if`) desyncs every field after it on decode.
+        // Round-tripping through postcard is what actually catches that,
+        // where a JSON-only test would not.
+        let encoded = postcard::to_stdvec(&game).expect("encode postcard");
+        let decoded: Game = postcard::from_bytes(&encoded).expect("decode postcard");
+
+        assert_eq!(game.variant, decoded.variant);
+        assert_eq!(game.speed, decoded.speed);
+        assert_eq!(game.fen, decoded.fen);
+        assert_eq!(game.id, decoded.id);
+        assert_eq!(game.date, decoded.date);
+        assert_eq!(game.white, decoded.white);
+        assert_eq!(game.black, decoded.black);
+        assert_eq!(game.winner, decoded.winner);
+        assert_eq!(san_strings(&game.moves), san_strings(&decoded.moves));
+        assert_eq!(game.ply, decoded.ply);
+        assert_eq!(game.packed_moves, decoded.packed_moves);
+    }
+
+    #[test]
+    fn game_postcard_roundtrip_without_packed_moves() {
+        assert_game_roundtrips(Game {
+            variant: Some("Standard".to_owned()),
+            speed: Some(Speed::Blitz),
+            fen: None,
+            id: Some("abcd1234".to_owned()),
+            date: Some("2023-01-01".to_owned()),
+            white: Player {
+                name: Some("alice".to_owned()),
+                rating: Some(2000),
+            },
+            black: Player {
+                name: None,
+                rating: None,
+            },
+            winner: Some(Color::White),
+            moves: sans(&["e4", "e5", "Nf3"]),
+            ply: None,
+            packed_moves: None,
+        });
+    }
+
+    #[test]
+    fn game_postcard_roundtrip_with_packed_moves() {
+        let moves = sans(&["e4", "e5", "Nf3", "Nc6"]);
+        let packed = pack_moves(None, &moves).expect("replays cleanly");
+        assert_game_roundtrips(Game {
+            variant: Some("Standard".to_owned()),
+            speed: Some(Speed::Correspondence),
+            fen: None,
+            id: None,
+            date: None,
+            white: Player::default(),
+            black: Player::default(),
+            winner: None,
+            moves: Vec::new(),
+            ply: Some(moves.len() as u16),
+            packed_moves: Some(packed),
+        });
+    }
+}
@@ -4,11 +4,14 @@ use axum::{
     response::IntoResponse,
 };
 use bytes::Bytes;
+use flate2::{write::GzEncoder, Compression};
 use futures_util::stream::Stream;
 use pin_project_lite::pin_project;
 use serde::{Deserialize, Serialize};
 use shakmaty::ByColor;
 use std::{
+    io::Write,
+    mem,
     pin::Pin,
     task::{Context, Poll},
 };
@@ -27,13 +30,106 @@ pub trait NevermindExt: Sized {
 
 impl<T, E> NevermindExt for Result<T, E> {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContentEncoding {
+    Identity,
+    Gzip,
+    Zstd,
+}
+
+impl ContentEncoding {
+    fn negotiate(headers: &HeaderMap) -> ContentEncoding {
+        let accepted = headers
+            .get(axum::http::header::ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+
+        if accepted
+            .split(',')
+            .any(|encoding| encoding.trim().starts_with("zstd"))
+        {
+            ContentEncoding::Zstd
+        } else if accepted
+            .split(',')
+            .any(|encoding| encoding.trim().starts_with("gzip"))
+        {
+            ContentEncoding::Gzip
+        } else {
+            ContentEncoding::Identity
+        }
+    }
+
+    fn header_value(self) -> Option<&'static str> {
+        match self {
+            ContentEncoding::Identity => None,
+            ContentEncoding::Gzip => Some("gzip"),
+            ContentEncoding::Zstd => Some("zstd"),
+        }
+    }
+}
+
+enum Encoder {
+    Identity,
+    Gzip(GzEncoder<Vec<u8>>),
+    Zstd(Box<zstd::stream::write::Encoder<'static, Vec<u8>>>),
+}
+
+impl Encoder {
+    fn new(encoding: ContentEncoding) -> Encoder {
+        match encoding {
+            ContentEncoding::Identity => Encoder::Identity,
+            ContentEncoding::Gzip => Encoder::Gzip(GzEncoder::new(Vec::new(), Compression::fast())),
+            ContentEncoding::Zstd => Encoder::Zstd(Box::new(
+                zstd::stream::write::Encoder::new(Vec::new(), 0).expect("zstd encoder"),
+            )),
+        }
+    }
+
+    fn encode(&mut self, buf: Vec<u8>) -> Bytes {
+        match self {
+            Encoder::Identity => Bytes::from(buf),
+            Encoder::Gzip(enc) => {
+                enc.write_all(&buf).expect("gzip write");
+                enc.flush().expect("gzip flush");
+                Bytes::from(mem::take(enc.get_mut()))
+            }
+            Encoder::Zstd(enc) => {
+                enc.write_all(&buf).expect("zstd write");
+                enc.flush().expect("zstd flush");
+                Bytes::from(mem::take(enc.get_mut()))
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Bytes {
+        match mem::replace(self, Encoder::Identity) {
+            Encoder::Identity => Bytes::new(),
+            Encoder::Gzip(enc) => Bytes::from(enc.finish().expect("gzip finish")),
+            Encoder::Zstd(enc) => Bytes::from(enc.finish().expect("zstd finish")),
+        }
+    }
+}
+
 pub struct NdJson<S> {
     stream: S,
+    encoding: ContentEncoding,
 }
 
 impl<S> NdJson<S> {
     pub fn new(stream: S) -> NdJson<S> {
-        NdJson { stream }
+        NdJson {
+            stream,
+            encoding: ContentEncoding::Identity,
+        }
+    }
+
+    /// Like `NdJson::new()`, but transparently compresses the body if the
+    /// request's `Accept-Encoding` header allows it.
+    pub fn with_request_headers(stream: S, headers: &HeaderMap) -> NdJson<S> {
+        NdJson {
+            stream,
+            encoding: ContentEncoding::negotiate(headers),
+        }
     }
 }
 
@@ -46,10 +142,18 @@ where
     type BodyError = serde_json::Error;
 
     fn into_response(self) -> Response<NdJsonBody<S>> {
-        Response::builder()
-            .header(axum::http::header::CONTENT_TYPE, "application/x-ndjson")
+        let mut builder =
+            Response::builder().header(axum::http::header::CONTENT_TYPE, "application/x-ndjson");
+
+        if let Some(value) = self.encoding.header_value() {
+            builder = builder.header(axum::http::header::CONTENT_ENCODING, value);
+        }
+
+        builder
             .body(NdJsonBody {
                 stream: SyncWrapper::new(self.stream),
+                encoder: Encoder::new(self.encoding),
+                done: false,
             })
             .unwrap()
     }
@@ -59,6 +163,8 @@ pin_project! {
     pub struct NdJsonBody<S> {
         #[pin]
         stream: SyncWrapper<S>,
+        encoder: Encoder,
+        done: bool,
     }
 }
 
@@ -74,17 +180,31 @@ where
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
-        self.project()
-            .stream
+        let this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        this.stream
             .get_pin_mut()
             .poll_next(cx)
-            .map(|item| {
-                item.map(|item| {
-                    serde_json::to_vec(&item).map(|mut buf| {
-                        buf.push(b'\n');
-                        Bytes::from(buf)
-                    })
-                })
+            .map(|item| match item {
+                Some(item) => Some(serde_json::to_vec(&item).map(|mut buf| {
+                    buf.push(b'\n');
+                    this.encoder.encode(buf)
+                })),
+                None => {
+                    *this.done = true;
+
+                    // Identity has no trailer to flush, so end the stream
+                    // directly instead of emitting a synthetic empty data frame.
+                    if matches!(this.encoder, Encoder::Identity) {
+                        None
+                    } else {
+                        Some(Ok(this.encoder.finish()))
+                    }
+                }
             })
     }
 